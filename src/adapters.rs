@@ -0,0 +1,425 @@
+use reqwest::Url;
+use scraper::{Html, Selector};
+
+/// The gallery-level metadata a [`SiteAdapter`] can scrape from a gallery's
+/// listing page, beyond the title and image links. Fields the site doesn't
+/// expose are left empty/`None` rather than guessed at.
+#[derive(Debug, Default, Clone)]
+pub struct GalleryMetadataFields {
+    pub uploader: Option<String>,
+    pub category: Option<String>,
+    pub tags: Vec<String>,
+    pub page_count: Option<u32>,
+}
+
+/// Site-specific knowledge needed to pull a gallery apart: its title, the
+/// per-image page links, pagination, and how to resolve the actual image
+/// bytes from an image page. `Gallery` is otherwise host-agnostic; adding a
+/// new site means adding a new `SiteAdapter` impl and wiring it up in
+/// [`adapter_for_url`].
+pub trait SiteAdapter: Send + Sync {
+    /// Human-readable name, used in error messages and debug output.
+    fn name(&self) -> &'static str;
+
+    /// Parse the gallery title from a gallery listing page.
+    fn parse_title(&self, document: &Html) -> Option<String>;
+
+    /// Parse the per-image page links from a gallery listing page, in
+    /// reading order.
+    fn parse_image_page_urls(&self, document: &Html) -> Vec<Url>;
+
+    /// Find the link to the next page of a paginated gallery listing, if
+    /// any.
+    fn next_page(&self, document: &Html) -> Option<Url>;
+
+    /// Resolve the URL of the actual image from an image page. When
+    /// `original` is true, prefer the site's full-resolution variant if it
+    /// exposes one.
+    fn resolve_full_image(&self, document: &Html, original: bool) -> Option<Url>;
+
+    /// Whether the URL returned by `resolve_full_image` for an original
+    /// image is itself a redirect that must be followed to reach the final
+    /// CDN link, rather than the final link already.
+    fn original_link_needs_redirect(&self) -> bool {
+        false
+    }
+
+    /// Whether `url` points at a single gallery, as opposed to a search or
+    /// tag-listing page.
+    fn is_gallery_url(&self, url: &Url) -> bool;
+
+    /// Parse the gallery links out of a search/tag-listing results page.
+    fn parse_listing_gallery_urls(&self, document: &Html) -> Vec<Url>;
+
+    /// Parse whatever uploader/category/tags/page-count metadata a gallery
+    /// listing page exposes, for the `metadata.json` manifest.
+    fn parse_gallery_metadata(&self, document: &Html) -> GalleryMetadataFields;
+}
+
+/// Adapter for e-hentai.org and its mirror exhentai.org.
+pub struct EHentaiAdapter;
+
+impl SiteAdapter for EHentaiAdapter {
+    fn name(&self) -> &'static str {
+        "e-hentai"
+    }
+
+    fn parse_title(&self, document: &Html) -> Option<String> {
+        let selector = Selector::parse("#gn").unwrap();
+        document.select(&selector).next().map(|e| e.inner_html())
+    }
+
+    fn parse_image_page_urls(&self, document: &Html) -> Vec<Url> {
+        let selector = Selector::parse("#gdt a").unwrap();
+        document
+            .select(&selector)
+            .filter_map(|e| e.value().attr("href"))
+            .filter_map(|href| Url::parse(href).ok())
+            .collect()
+    }
+
+    fn next_page(&self, document: &Html) -> Option<Url> {
+        let selector = Selector::parse("table.ptt > tbody > tr > td:last-child > a").unwrap();
+        document
+            .select(&selector)
+            .next()
+            .and_then(|e| e.value().attr("href"))
+            .and_then(|href| Url::parse(href).ok())
+    }
+
+    fn resolve_full_image(&self, document: &Html, original: bool) -> Option<Url> {
+        if original {
+            let selector = Selector::parse("div#i6 div:last-child a").unwrap();
+            if let Some(href) = document
+                .select(&selector)
+                .next()
+                .and_then(|e| e.value().attr("href"))
+            {
+                return Url::parse(href).ok();
+            }
+        }
+
+        let selector = Selector::parse("div#i3 a img").unwrap();
+        document
+            .select(&selector)
+            .next()
+            .and_then(|e| e.value().attr("src"))
+            .and_then(|src| Url::parse(src).ok())
+    }
+
+    fn original_link_needs_redirect(&self) -> bool {
+        true
+    }
+
+    fn is_gallery_url(&self, url: &Url) -> bool {
+        url.path_segments()
+            .is_some_and(|mut segs| segs.next() == Some("g"))
+    }
+
+    fn parse_listing_gallery_urls(&self, document: &Html) -> Vec<Url> {
+        let selector = Selector::parse("table.itg a").unwrap();
+        document
+            .select(&selector)
+            .filter_map(|e| e.value().attr("href"))
+            .filter_map(|href| Url::parse(href).ok())
+            .filter(|url| self.is_gallery_url(url))
+            .collect()
+    }
+
+    fn parse_gallery_metadata(&self, document: &Html) -> GalleryMetadataFields {
+        let uploader_selector = Selector::parse("#gdn a").unwrap();
+        let uploader = document
+            .select(&uploader_selector)
+            .next()
+            .map(|e| e.inner_html());
+
+        let category_selector = Selector::parse("#gdc .cs").unwrap();
+        let category = document
+            .select(&category_selector)
+            .next()
+            .map(|e| e.inner_html());
+
+        let tag_selector = Selector::parse("#taglist a").unwrap();
+        let tags = document
+            .select(&tag_selector)
+            .map(|e| e.inner_html())
+            .collect();
+
+        let row_selector = Selector::parse("#gdd tr").unwrap();
+        let page_count = document.select(&row_selector).find_map(|row| {
+            let text = row.text().collect::<String>();
+            let (_, after) = text.split_once("Length:")?;
+            after.split_whitespace().next()?.parse().ok()
+        });
+
+        GalleryMetadataFields {
+            uploader,
+            category,
+            tags,
+            page_count,
+        }
+    }
+}
+
+/// Adapter for nhentai.net, a doujin gallery site with a different markup
+/// shape than e-hentai but no separate "original" resolution tier.
+pub struct NhentaiAdapter;
+
+impl SiteAdapter for NhentaiAdapter {
+    fn name(&self) -> &'static str {
+        "nhentai"
+    }
+
+    fn parse_title(&self, document: &Html) -> Option<String> {
+        let selector = Selector::parse("#info h1.title").unwrap();
+        document.select(&selector).next().map(|e| e.inner_html())
+    }
+
+    fn parse_image_page_urls(&self, document: &Html) -> Vec<Url> {
+        let selector = Selector::parse(".thumb-container a").unwrap();
+        document
+            .select(&selector)
+            .filter_map(|e| e.value().attr("href"))
+            .filter_map(|href| Url::parse(href).ok())
+            .collect()
+    }
+
+    fn next_page(&self, document: &Html) -> Option<Url> {
+        let selector = Selector::parse("a.next").unwrap();
+        document
+            .select(&selector)
+            .next()
+            .and_then(|e| e.value().attr("href"))
+            .and_then(|href| Url::parse(href).ok())
+    }
+
+    fn resolve_full_image(&self, document: &Html, _original: bool) -> Option<Url> {
+        let selector = Selector::parse("#image-container img").unwrap();
+        document
+            .select(&selector)
+            .next()
+            .and_then(|e| e.value().attr("src"))
+            .and_then(|src| Url::parse(src).ok())
+    }
+
+    fn is_gallery_url(&self, url: &Url) -> bool {
+        url.path_segments()
+            .is_some_and(|mut segs| segs.next() == Some("g"))
+    }
+
+    fn parse_listing_gallery_urls(&self, document: &Html) -> Vec<Url> {
+        let selector = Selector::parse(".gallery a").unwrap();
+        document
+            .select(&selector)
+            .filter_map(|e| e.value().attr("href"))
+            .filter_map(|href| Url::parse(href).ok())
+            .filter(|url| self.is_gallery_url(url))
+            .collect()
+    }
+
+    fn parse_gallery_metadata(&self, document: &Html) -> GalleryMetadataFields {
+        let tag_selector = Selector::parse(".tag-container .tag .name").unwrap();
+        let tags = document
+            .select(&tag_selector)
+            .map(|e| e.inner_html())
+            .collect();
+
+        let page_selector = Selector::parse(".tag-container .pages .name").unwrap();
+        let page_count = document
+            .select(&page_selector)
+            .next()
+            .and_then(|e| e.inner_html().parse().ok());
+
+        // nhentai doesn't expose an uploader or a category distinct from
+        // its tags, so those stay `None`.
+        GalleryMetadataFields {
+            uploader: None,
+            category: None,
+            tags,
+            page_count,
+        }
+    }
+}
+
+/// Pick the adapter for a gallery URL by inspecting its host, so a single
+/// input file can mix galleries from different supported sites.
+pub fn adapter_for_url(url: &Url) -> anyhow::Result<Box<dyn SiteAdapter>> {
+    match url.host_str() {
+        Some("e-hentai.org") | Some("exhentai.org") => Ok(Box::new(EHentaiAdapter)),
+        Some("nhentai.net") => Ok(Box::new(NhentaiAdapter)),
+        Some(host) => anyhow::bail!("Unsupported gallery host: {}", host),
+        None => anyhow::bail!("Gallery URL has no host: {}", url),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adapter_for_url_known_hosts() {
+        let url = Url::parse("https://e-hentai.org/g/1/1/").unwrap();
+        assert_eq!(adapter_for_url(&url).unwrap().name(), "e-hentai");
+
+        let url = Url::parse("https://exhentai.org/g/1/1/").unwrap();
+        assert_eq!(adapter_for_url(&url).unwrap().name(), "e-hentai");
+
+        let url = Url::parse("https://nhentai.net/g/1/").unwrap();
+        assert_eq!(adapter_for_url(&url).unwrap().name(), "nhentai");
+    }
+
+    #[test]
+    fn test_is_gallery_url() {
+        let adapter = EHentaiAdapter;
+        assert!(adapter.is_gallery_url(&Url::parse("https://e-hentai.org/g/1/1/").unwrap()));
+        assert!(!adapter.is_gallery_url(&Url::parse("https://e-hentai.org/?f_search=tag").unwrap()));
+    }
+
+    #[test]
+    fn test_adapter_for_url_unknown_host() {
+        let url = Url::parse("https://example.com/gallery").unwrap();
+        assert!(adapter_for_url(&url).is_err());
+    }
+
+    #[test]
+    fn test_ehentai_parse_gallery_metadata() {
+        let html = r#"
+            <div id="gdn"><a>uploader_name</a></div>
+            <div id="gdc"><span class="cs">Doujinshi</span></div>
+            <div id="taglist"><a>language:translated</a><a>parody:original</a></div>
+            <div id="gdd"><table><tr><td>Length:</td><td>23 pages</td></tr></table></div>
+        "#;
+        let document = Html::parse_document(html);
+        let metadata = EHentaiAdapter.parse_gallery_metadata(&document);
+
+        assert_eq!(metadata.uploader.as_deref(), Some("uploader_name"));
+        assert_eq!(metadata.category.as_deref(), Some("Doujinshi"));
+        assert_eq!(metadata.tags, vec!["language:translated", "parody:original"]);
+        assert_eq!(metadata.page_count, Some(23));
+    }
+
+    #[test]
+    fn test_ehentai_parse_image_page_urls() {
+        let html = r#"
+            <div id="gdt">
+                <a href="https://e-hentai.org/s/abc123/1-1"><img/></a>
+                <a href="https://e-hentai.org/s/def456/1-2"><img/></a>
+            </div>
+        "#;
+        let document = Html::parse_document(html);
+        let urls = EHentaiAdapter.parse_image_page_urls(&document);
+
+        assert_eq!(
+            urls,
+            vec![
+                Url::parse("https://e-hentai.org/s/abc123/1-1").unwrap(),
+                Url::parse("https://e-hentai.org/s/def456/1-2").unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ehentai_next_page() {
+        let html = r#"
+            <table class="ptt">
+                <tbody>
+                    <tr>
+                        <td><a href="https://e-hentai.org/g/1/1/?p=0">1</a></td>
+                        <td><a href="https://e-hentai.org/g/1/1/?p=1">2</a></td>
+                    </tr>
+                </tbody>
+            </table>
+        "#;
+        let document = Html::parse_document(html);
+        let next = EHentaiAdapter.next_page(&document);
+
+        assert_eq!(
+            next,
+            Some(Url::parse("https://e-hentai.org/g/1/1/?p=1").unwrap())
+        );
+    }
+
+    #[test]
+    fn test_ehentai_next_page_none() {
+        let html = r#"<table class="ptt"><tbody><tr></tr></tbody></table>"#;
+        let document = Html::parse_document(html);
+        assert_eq!(EHentaiAdapter.next_page(&document), None);
+    }
+
+    #[test]
+    fn test_ehentai_parse_listing_gallery_urls() {
+        let html = r#"
+            <table class="itg">
+                <tr><td><a href="https://e-hentai.org/g/1/1/">Gallery 1</a></td></tr>
+                <tr><td><a href="https://e-hentai.org/?f_search=tag">search link</a></td></tr>
+                <tr><td><a href="https://e-hentai.org/g/2/2/">Gallery 2</a></td></tr>
+            </table>
+        "#;
+        let document = Html::parse_document(html);
+        let urls = EHentaiAdapter.parse_listing_gallery_urls(&document);
+
+        assert_eq!(
+            urls,
+            vec![
+                Url::parse("https://e-hentai.org/g/1/1/").unwrap(),
+                Url::parse("https://e-hentai.org/g/2/2/").unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nhentai_parse_image_page_urls() {
+        let html = r#"
+            <div class="thumb-container"><a href="https://nhentai.net/g/1/1/"><img/></a></div>
+            <div class="thumb-container"><a href="https://nhentai.net/g/1/2/"><img/></a></div>
+        "#;
+        let document = Html::parse_document(html);
+        let urls = NhentaiAdapter.parse_image_page_urls(&document);
+
+        assert_eq!(
+            urls,
+            vec![
+                Url::parse("https://nhentai.net/g/1/1/").unwrap(),
+                Url::parse("https://nhentai.net/g/1/2/").unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nhentai_next_page() {
+        let html = r#"<a class="next" href="https://nhentai.net/g/1/?page=2">next</a>"#;
+        let document = Html::parse_document(html);
+        let next = NhentaiAdapter.next_page(&document);
+
+        assert_eq!(
+            next,
+            Some(Url::parse("https://nhentai.net/g/1/?page=2").unwrap())
+        );
+    }
+
+    #[test]
+    fn test_nhentai_next_page_none() {
+        let html = "<div>no pagination here</div>";
+        let document = Html::parse_document(html);
+        assert_eq!(NhentaiAdapter.next_page(&document), None);
+    }
+
+    #[test]
+    fn test_nhentai_parse_listing_gallery_urls() {
+        let html = r#"
+            <div class="gallery"><a href="https://nhentai.net/g/1/">Gallery 1</a></div>
+            <div class="gallery"><a href="https://nhentai.net/search/?q=tag">search link</a></div>
+            <div class="gallery"><a href="https://nhentai.net/g/2/">Gallery 2</a></div>
+        "#;
+        let document = Html::parse_document(html);
+        let urls = NhentaiAdapter.parse_listing_gallery_urls(&document);
+
+        assert_eq!(
+            urls,
+            vec![
+                Url::parse("https://nhentai.net/g/1/").unwrap(),
+                Url::parse("https://nhentai.net/g/2/").unwrap(),
+            ]
+        );
+    }
+}
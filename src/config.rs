@@ -1,9 +1,10 @@
 use std::io::BufRead;
 
 use anyhow::Result;
+use reqwest::Url;
 use serde::{Deserialize, Serialize};
 
-use crate::gallery::Gallery;
+use crate::{CLIENT, adapters, gallery::Gallery, http};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Config {
@@ -18,6 +19,41 @@ pub struct Config {
     pub input: String,
 
     pub output: String,
+
+    #[serde(default)]
+    pub package: PackageFormat,
+
+    /// Maximum number of galleries a single search/tag-listing line in the
+    /// input file may expand into, so a broad query can't accidentally
+    /// enqueue thousands of galleries.
+    #[serde(default = "default_max_search_results")]
+    pub max_search_results: usize,
+
+    /// How many galleries may download concurrently. Image downloads of
+    /// all galleries together share the single `concurrency` budget.
+    #[serde(default = "default_gallery_concurrency")]
+    pub gallery_concurrency: usize,
+}
+
+fn default_max_search_results() -> usize {
+    100
+}
+
+fn default_gallery_concurrency() -> usize {
+    4
+}
+
+/// How a finished gallery's downloaded images should be packaged on disk.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PackageFormat {
+    /// Leave the images as a loose, numbered folder (the default).
+    #[default]
+    Folder,
+    /// Zip the images into a `<title>.cbz` comic book archive.
+    Cbz,
+    /// Build a minimal EPUB with one page per image.
+    Epub,
 }
 
 impl Config {
@@ -27,17 +63,115 @@ impl Config {
         Ok(config)
     }
 
-    pub fn get_links(&self) -> Result<Vec<Gallery>> {
+    /// Read the input file, expanding any search/tag-listing lines into
+    /// their constituent gallery links along the way.
+    pub async fn get_links(&self) -> Result<Vec<Gallery>> {
         let file = std::fs::File::open(&self.input)?;
         let reader = std::io::BufReader::new(file);
-        let links: Vec<Gallery> = reader
-            .lines()
-            .filter_map(|r| r.ok().and_then(|l| Gallery::new(l).ok()))
-            .collect();
-        Ok(links)
+
+        let mut galleries = Vec::new();
+        for line in reader.lines().map_while(Result::ok) {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let Ok(url) = Url::parse(line) else {
+                eprintln!("Skipping invalid URL: {line}");
+                continue;
+            };
+
+            let Ok(adapter) = adapters::adapter_for_url(&url) else {
+                eprintln!("Skipping unsupported URL: {line}");
+                continue;
+            };
+
+            if adapter.is_gallery_url(&url) {
+                if let Ok(gallery) = Gallery::new(line.to_string()) {
+                    galleries.push(gallery);
+                }
+                continue;
+            }
+
+            match self
+                .expand_search_url(url, adapter.as_ref(), self.max_search_results)
+                .await
+            {
+                Ok(expanded) => galleries.extend(expanded),
+                Err(e) => eprintln!("Error expanding search URL {line}: {e}"),
+            }
+        }
+
+        Ok(galleries)
+    }
+
+    /// Follow a search/tag-listing page's pagination, collecting the
+    /// gallery links it lists, up to `limit` galleries.
+    async fn expand_search_url(
+        &self,
+        url: Url,
+        adapter: &dyn adapters::SiteAdapter,
+        limit: usize,
+    ) -> Result<Vec<Gallery>> {
+        let mut gallery_urls = Vec::new();
+        let mut next = Some(url);
+
+        while let Some(page_url) = next.take() {
+            if gallery_urls.len() >= limit {
+                break;
+            }
+
+            let request = CLIENT
+                .get()
+                .unwrap()
+                .get(page_url.as_str())
+                .header("Cookie", &self.cookie);
+            let response = http::send_with_retry(request).await?;
+
+            if !response.status().is_success() {
+                anyhow::bail!(
+                    "Failed to fetch search listing, status: {}",
+                    response.status()
+                );
+            }
+
+            let document = scraper::Html::parse_document(&response.text().await?);
+            let reached_cap = collect_listing_page(&document, adapter, &mut gallery_urls, limit);
+
+            if !reached_cap {
+                next = adapter.next_page(&document);
+            }
+        }
+
+        Ok(gallery_urls
+            .into_iter()
+            .filter_map(|gallery_url| Gallery::new(gallery_url.to_string()).ok())
+            .collect())
     }
 }
 
+/// Collect a parsed listing page's gallery URLs into `galleries`, up to
+/// `limit`, returning whether the cap was reached. Split out as a pure
+/// function over already-parsed HTML so it can be unit-tested against
+/// fixture pages without a live network call, the same way
+/// `parse_listing_gallery_urls`/`next_page` are tested in isolation in
+/// `adapters.rs`.
+fn collect_listing_page(
+    document: &scraper::Html,
+    adapter: &dyn adapters::SiteAdapter,
+    galleries: &mut Vec<Url>,
+    limit: usize,
+) -> bool {
+    for gallery_url in adapter.parse_listing_gallery_urls(document) {
+        if galleries.len() >= limit {
+            return true;
+        }
+        galleries.push(gallery_url);
+    }
+
+    galleries.len() >= limit
+}
+
 #[cfg(test)]
 mod tests {
     use serde_json::json;
@@ -92,4 +226,57 @@ mod tests {
             assert!(e.to_string().contains("missing field `input`"));
         }
     }
+
+    #[test]
+    fn test_collect_listing_page_stops_at_limit() {
+        let html = r#"
+            <table class="itg">
+                <tr><td><a href="https://e-hentai.org/g/1/1/">Gallery 1</a></td></tr>
+                <tr><td><a href="https://e-hentai.org/g/2/2/">Gallery 2</a></td></tr>
+                <tr><td><a href="https://e-hentai.org/g/3/3/">Gallery 3</a></td></tr>
+            </table>
+        "#;
+        let document = scraper::Html::parse_document(html);
+        let mut galleries = Vec::new();
+
+        let reached_cap =
+            collect_listing_page(&document, &adapters::EHentaiAdapter, &mut galleries, 2);
+
+        assert!(reached_cap);
+        assert_eq!(galleries.len(), 2);
+    }
+
+    #[test]
+    fn test_collect_listing_page_under_limit() {
+        let html = r#"
+            <table class="itg">
+                <tr><td><a href="https://e-hentai.org/g/1/1/">Gallery 1</a></td></tr>
+            </table>
+        "#;
+        let document = scraper::Html::parse_document(html);
+        let mut galleries = Vec::new();
+
+        let reached_cap =
+            collect_listing_page(&document, &adapters::EHentaiAdapter, &mut galleries, 5);
+
+        assert!(!reached_cap);
+        assert_eq!(galleries.len(), 1);
+    }
+
+    #[test]
+    fn test_collect_listing_page_respects_already_collected() {
+        let html = r#"
+            <table class="itg">
+                <tr><td><a href="https://e-hentai.org/g/2/2/">Gallery 2</a></td></tr>
+            </table>
+        "#;
+        let document = scraper::Html::parse_document(html);
+        let mut galleries = vec![Url::parse("https://e-hentai.org/g/1/1/").unwrap()];
+
+        let reached_cap =
+            collect_listing_page(&document, &adapters::EHentaiAdapter, &mut galleries, 1);
+
+        assert!(reached_cap);
+        assert_eq!(galleries.len(), 1);
+    }
 }
@@ -1,36 +1,63 @@
 use anyhow::Result;
+use futures_util::StreamExt;
 use indicatif::ProgressBar;
-use reqwest::Url;
-use retrying::retry;
-use std::{io::Write, path::PathBuf, sync::Arc, time::Duration};
-use tokio::task::JoinSet;
+use reqwest::{
+    Url,
+    header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, LOCATION},
+};
+use std::{path::PathBuf, sync::Arc, time::Duration};
+use tokio::{io::AsyncWriteExt, task::JoinSet};
+
+use crate::{
+    CLIENT, PB, SEM,
+    adapters::{self, GalleryMetadataFields, SiteAdapter},
+    config::Config,
+    error, http,
+    index::{ImageCacheEntry, ImageIndex},
+    info,
+    metadata::{GalleryMetadata, ImageManifestEntry},
+};
 
-use crate::{CLIENT, PB, SEM, config::Config, error, info};
-
-#[derive(Debug)]
 pub struct Gallery {
     pub url: Url,
     pub title: String,
     pub images: Vec<Url>,
+    adapter: Arc<dyn SiteAdapter>,
+    metadata: GalleryMetadataFields,
+}
+
+impl std::fmt::Debug for Gallery {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Gallery")
+            .field("url", &self.url)
+            .field("title", &self.title)
+            .field("images", &self.images)
+            .field("adapter", &self.adapter.name())
+            .finish()
+    }
 }
 
 impl Gallery {
     pub fn new(url: String) -> Result<Self> {
+        let url = Url::parse(&url)?;
+        let adapter = Arc::from(adapters::adapter_for_url(&url)?);
+
         Ok(Gallery {
-            url: Url::parse(&url)?,
+            url,
             title: String::new(),
             images: Vec::new(),
+            adapter,
+            metadata: GalleryMetadataFields::default(),
         })
     }
 
     pub async fn fetch_info(&mut self, config: Arc<Config>) -> Result<()> {
-        let response = CLIENT
+        let request = CLIENT
             .get()
             .unwrap()
             .get(self.url.as_str())
-            .header("Cookie", &config.cookie)
-            .send()
-            .await?;
+            .header("Cookie", &config.cookie);
+        let response = http::send_with_retry(request).await?;
 
         if !response.status().is_success() {
             anyhow::bail!(
@@ -40,17 +67,15 @@ impl Gallery {
         }
 
         let document = scraper::Html::parse_document(&response.text().await?);
-        let title = document
-            .select(&scraper::Selector::parse("#gn").unwrap())
-            .next()
-            .map(|e| e.inner_html());
 
-        if let Some(title) = title {
+        if let Some(title) = self.adapter.parse_title(&document) {
             self.title = title;
         } else {
             return Err(anyhow::anyhow!("Failed to find gallery title"));
         }
 
+        self.metadata = self.adapter.parse_gallery_metadata(&document);
+
         Ok(())
     }
 
@@ -58,42 +83,22 @@ impl Gallery {
         let mut url = self.url.to_string();
 
         loop {
-            let response = CLIENT
+            let request = CLIENT
                 .get()
                 .unwrap()
                 .get(url)
-                .header("Cookie", &config.cookie)
-                .send()
-                .await?
-                .text()
-                .await?;
+                .header("Cookie", &config.cookie);
+            let response = http::send_with_retry(request).await?.text().await?;
 
             let document = scraper::Html::parse_document(&response);
-            let selector = scraper::Selector::parse("#gdt a").unwrap();
 
-            for (index, element) in document.select(&selector).enumerate() {
-                if let Some(src) = element.value().attr("href") {
-                    if let Ok(image_url) = Url::parse(src) {
-                        self.images.insert(index, image_url);
-                    }
-                }
-            }
+            self.images
+                .extend(self.adapter.parse_image_page_urls(&document));
 
             // Check for next page link
-            if let Some(next_page) = document
-                .select(
-                    &scraper::Selector::parse("table.ptt > tbody > tr > td:last-child > a")
-                        .unwrap(),
-                )
-                .next()
-            {
-                if let Some(href) = next_page.value().attr("href") {
-                    url = href.to_string();
-                } else {
-                    break;
-                }
-            } else {
-                break;
+            match self.adapter.next_page(&document) {
+                Some(next_page) => url = next_page.to_string(),
+                None => break,
             }
         }
 
@@ -126,16 +131,29 @@ impl Gallery {
                 .replace(['/', '\\', ':', '*', '?', '"', '<', '>', '|'], ""),
         );
 
-        for (index, image_url) in self.images.into_iter().enumerate() {
+        let output_dir = PathBuf::from(&format!("{}/{}", config.output, title));
+        let index_path = output_dir.with_file_name(format!("{title}.index.json"));
+        let index = ImageIndex::load(index_path);
+
+        for (index_no, image_url) in self.images.into_iter().enumerate() {
             let title = Arc::clone(&title);
             let config = Arc::clone(&config);
             let pb = Arc::clone(&pb);
+            let adapter = Arc::clone(&self.adapter);
+            let image_index = Arc::clone(&index);
             tasks.spawn(async move {
                 let _limit = SEM.get().unwrap().acquire().await;
-                pb.set_message(format!("Downloading image {}", index + 1));
-                download(index, title, image_url.clone(), config)
-                    .await
-                    .unwrap_or_else(|e| error!("Failed to download image {}: {}", image_url, e));
+                pb.set_message(format!("Downloading image {}", index_no + 1));
+                download(
+                    index_no,
+                    title,
+                    image_url.clone(),
+                    config,
+                    adapter,
+                    image_index,
+                )
+                .await
+                .unwrap_or_else(|e| error!("Failed to download image {}: {}", image_url, e));
                 pb.inc(1);
             });
         }
@@ -143,19 +161,59 @@ impl Gallery {
         tasks.join_all().await;
 
         pb.finish_and_clear();
+
+        let images = crate::package::numbered_images(&output_dir)
+            .unwrap_or_default()
+            .into_iter()
+            .enumerate()
+            .map(|(i, path)| ImageManifestEntry {
+                // Files are named after their page number, so a page that
+                // failed to download leaves a gap instead of shifting later
+                // pages down.
+                page: path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .and_then(|stem| stem.parse().ok())
+                    .unwrap_or(i + 1),
+                file_name: path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .unwrap_or_default()
+                    .to_string(),
+            })
+            .collect();
+        let manifest = GalleryMetadata::new(
+            title.to_string(),
+            self.url.to_string(),
+            self.metadata,
+            images,
+        );
+        if let Err(e) = manifest.write_to(&output_dir) {
+            error!("Failed to write metadata manifest for {}: {}", title, e);
+        }
+
+        if let Err(e) =
+            crate::package::package_gallery(&config, &title, self.url.as_str(), &output_dir)
+        {
+            error!("Failed to package gallery {}: {}", title, e);
+        }
     }
 }
 
-#[retry(stop = attempts(3))]
-async fn download(index: usize, title: Arc<String>, url: Url, config: Arc<Config>) -> Result<()> {
-    let response = CLIENT
+async fn download(
+    index: usize,
+    title: Arc<String>,
+    url: Url,
+    config: Arc<Config>,
+    adapter: Arc<dyn SiteAdapter>,
+    image_index: Arc<ImageIndex>,
+) -> Result<()> {
+    let request = CLIENT
         .get()
         .unwrap()
         .get(url.as_str())
-        .header("Cookie", &config.cookie)
-        .send()
-        .await
-        .expect("Failed to send request");
+        .header("Cookie", &config.cookie);
+    let response = http::send_with_retry(request).await?;
 
     if !response.status().is_success() {
         error!(
@@ -166,46 +224,25 @@ async fn download(index: usize, title: Arc<String>, url: Url, config: Arc<Config
     }
 
     let text = response.text().await?;
+    let document = scraper::Html::parse_document(&text);
 
-    let mut image_url = String::new();
-
-    {
-        let selector = scraper::Selector::parse("div#i3 a img").unwrap();
-        let document = scraper::Html::parse_document(&text);
-        if let Some(element) = document.select(&selector).next() {
-            if let Some(src) = element.value().attr("src") {
-                image_url = src.to_owned();
-            }
-        }
-    }
-
-    if config.original {
-        let mut has_origin = false;
-        {
-            let document = scraper::Html::parse_document(&text);
-            let selector = scraper::Selector::parse("div#i6 div:last-child a").unwrap();
-            if let Some(element) = document.select(&selector).next() {
-                if let Some(href) = element.value().attr("href") {
-                    image_url = href.to_string();
-                    has_origin = true;
-                }
-            }
-        }
+    let mut image_url = adapter
+        .resolve_full_image(&document, config.original)
+        .map(|u| u.to_string())
+        .unwrap_or_default();
 
-        if has_origin {
-            let redirect_url = CLIENT
-                .get()
-                .unwrap()
-                .get(image_url.as_str())
-                .header("Cookie", &config.cookie)
-                .send()
-                .await?;
-
-            if redirect_url.status().is_redirection() {
-                if let Some(location) = redirect_url.headers().get(reqwest::header::LOCATION) {
-                    if let Ok(loc_str) = location.to_str() {
-                        image_url = loc_str.to_string();
-                    }
+    if config.original && !image_url.is_empty() && adapter.original_link_needs_redirect() {
+        let request = CLIENT
+            .get()
+            .unwrap()
+            .get(image_url.as_str())
+            .header("Cookie", &config.cookie);
+        let redirect_url = http::send_with_retry(request).await?;
+
+        if redirect_url.status().is_redirection() {
+            if let Some(location) = redirect_url.headers().get(LOCATION) {
+                if let Ok(loc_str) = location.to_str() {
+                    image_url = loc_str.to_string();
                 }
             }
         }
@@ -227,12 +264,43 @@ async fn download(index: usize, title: Arc<String>, url: Url, config: Arc<Config
         return Ok(());
     }
 
-    let response = CLIENT
+    let mut request = CLIENT
         .get()
         .ok_or(anyhow::anyhow!("Failed to create request for image"))?
-        .get(&image_url)
-        .send()
-        .await?;
+        .get(&image_url);
+
+    if let Some(cached) = image_index.get(&image_url).await {
+        if let Some(etag) = &cached.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &cached.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    let mut response = http::send_with_retry(request).await?;
+
+    if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+        // The cache entry is still valid, but only trust it if the bytes it
+        // describes are actually still on disk. A packaged gallery has its
+        // `output_dir` deleted after archiving while the ETag sidecar
+        // deliberately survives (see `ImageIndex`), so re-running a
+        // packaged gallery would otherwise accept 304s for files that no
+        // longer exist and package an empty archive over the old one.
+        if file_path.exists() {
+            info!(
+                "Image {} not modified since last run, treating as already downloaded",
+                index + 1
+            );
+            return Ok(());
+        }
+
+        let request = CLIENT
+            .get()
+            .ok_or(anyhow::anyhow!("Failed to create request for image"))?
+            .get(&image_url);
+        response = http::send_with_retry(request).await?;
+    }
 
     if !response.status().is_success() {
         error!(
@@ -243,11 +311,53 @@ async fn download(index: usize, title: Arc<String>, url: Url, config: Arc<Config
         anyhow::bail!("Failed to download image");
     }
 
-    let mut file = std::fs::File::create(&file_path).expect("Failed to create file");
+    let etag = response
+        .headers()
+        .get(ETAG)
+        .and_then(|v| v.to_str().ok())
+        .map(String::from);
+    let last_modified = response
+        .headers()
+        .get(LAST_MODIFIED)
+        .and_then(|v| v.to_str().ok())
+        .map(String::from);
+
+    let total_size = response.content_length().unwrap_or(0);
+    let byte_pb = PB.add(ProgressBar::new(total_size));
+    byte_pb.set_style(
+        indicatif::ProgressStyle::default_bar()
+            .template("  [{bar:40.green/blue}] {bytes}/{total_bytes} ({bytes_per_sec}) {msg}")
+            .unwrap()
+            .progress_chars("=>-"),
+    );
+    byte_pb.set_message(format!("image {}", index + 1));
 
-    let content = response.bytes().await?;
+    let part_path = file_path.with_extension(format!("{ext}.part"));
+    let mut file = tokio::fs::File::create(&part_path)
+        .await
+        .expect("Failed to create file");
+
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        file.write_all(&chunk)
+            .await
+            .expect("Failed to write to file");
+        byte_pb.inc(chunk.len() as u64);
+    }
+    file.flush().await.expect("Failed to flush file");
+    drop(file);
+
+    tokio::fs::rename(&part_path, &file_path)
+        .await
+        .expect("Failed to finalize downloaded file");
+    byte_pb.finish_and_clear();
 
-    file.write_all(&content).expect("Failed to write to file");
+    if etag.is_some() || last_modified.is_some() {
+        image_index
+            .set(image_url, ImageCacheEntry { etag, last_modified })
+            .await?;
+    }
 
     Ok(())
 }
@@ -258,8 +368,14 @@ mod tests {
 
     #[test]
     fn test_new_gallery() {
-        let url = "https://example.com/gallery".to_string();
+        let url = "https://e-hentai.org/g/1/1/".to_string();
         let gallery = Gallery::new(url).unwrap();
-        assert_eq!(gallery.url.as_str(), "https://example.com/gallery");
+        assert_eq!(gallery.url.as_str(), "https://e-hentai.org/g/1/1/");
+    }
+
+    #[test]
+    fn test_new_gallery_unsupported_host() {
+        let url = "https://example.com/gallery".to_string();
+        assert!(Gallery::new(url).is_err());
     }
 }
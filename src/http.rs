@@ -0,0 +1,150 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use reqwest::{RequestBuilder, Response, StatusCode, header::HeaderMap, header::RETRY_AFTER};
+
+use crate::info;
+
+const MAX_ATTEMPTS: u32 = 5;
+const BASE_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Send a request, retrying on `429`/`5xx` responses and transport errors
+/// with capped exponential backoff, honoring the server's `Retry-After`
+/// header when present. Any other non-retryable status (including other
+/// `4xx`) is returned as-is so callers keep doing their own
+/// `response.status().is_success()` checks.
+///
+/// This is the single place `fetch_info`, `fetch_images`, and `download`
+/// go through to talk to e-hentai, so gallery quota errors get handled the
+/// same way everywhere.
+pub async fn send_with_retry(request: RequestBuilder) -> Result<Response> {
+    let mut attempt: u32 = 0;
+
+    loop {
+        attempt += 1;
+        let req = request
+            .try_clone()
+            .context("Request cannot be retried (body is a stream)")?;
+
+        match req.send().await {
+            Ok(response) => {
+                let status = response.status();
+                if !is_retryable_status(status) {
+                    return Ok(response);
+                }
+
+                if attempt >= MAX_ATTEMPTS {
+                    anyhow::bail!(
+                        "Giving up after {} attempts, last status: {}",
+                        attempt,
+                        status
+                    );
+                }
+
+                let delay = retry_after(response.headers()).unwrap_or_else(|| backoff(attempt));
+                info!(
+                    "Request throttled with status {}, retrying in {:.1}s",
+                    status,
+                    delay.as_secs_f32()
+                );
+                tokio::time::sleep(delay).await;
+            }
+            Err(e) => {
+                if attempt >= MAX_ATTEMPTS {
+                    return Err(e).context("Request failed after retries");
+                }
+                tokio::time::sleep(backoff(attempt)).await;
+            }
+        }
+    }
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Parse the `Retry-After` header, which is either a number of seconds or
+/// an HTTP-date.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?;
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = httpdate::parse_http_date(value).ok()?;
+    date.duration_since(std::time::SystemTime::now()).ok()
+}
+
+/// Capped exponential backoff with jitter, so a burst of concurrent
+/// retries doesn't all wake up and hammer the server at once.
+fn backoff(attempt: u32) -> Duration {
+    let exp = BASE_BACKOFF.saturating_mul(1u32 << attempt.min(6));
+    let capped = exp.min(MAX_BACKOFF);
+    capped + Duration::from_millis(jitter_ms(capped.as_millis() as u64 / 4 + 1))
+}
+
+fn jitter_ms(max: u64) -> u64 {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() as u64)
+        .unwrap_or(0);
+    nanos % max.max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_retry_after_seconds() {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("120"));
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(120)));
+    }
+
+    #[test]
+    fn test_retry_after_http_date() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Thu, 01 Jan 2099 00:00:00 GMT"),
+        );
+
+        let delay = retry_after(&headers).expect("HTTP-date form should parse");
+        // Just assert it parsed into something far in the future rather than
+        // pinning an exact duration, so the test doesn't rot with time.
+        assert!(delay > Duration::from_secs(60 * 60 * 24 * 365));
+    }
+
+    #[test]
+    fn test_retry_after_garbage() {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("not-a-valid-value"));
+        assert_eq!(retry_after(&headers), None);
+    }
+
+    #[test]
+    fn test_retry_after_missing() {
+        let headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+    }
+
+    #[test]
+    fn test_backoff_grows_with_attempt() {
+        assert!(backoff(1) < backoff(2));
+        assert!(backoff(2) < backoff(3));
+    }
+
+    #[test]
+    fn test_backoff_caps_at_max() {
+        let delay = backoff(20);
+        let max_with_jitter =
+            MAX_BACKOFF + Duration::from_millis(MAX_BACKOFF.as_millis() as u64 / 4 + 2);
+
+        assert!(delay >= MAX_BACKOFF);
+        assert!(delay <= max_with_jitter);
+    }
+}
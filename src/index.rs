@@ -0,0 +1,83 @@
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+/// The `ETag`/`Last-Modified` we last saw for a downloaded image, so a
+/// re-run can send conditional headers instead of re-fetching the bytes.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ImageCacheEntry {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+/// Per-gallery sidecar index, persisted as a JSON file alongside the
+/// gallery's output (a sibling of the output folder / archive, so it
+/// survives packaging into a CBZ/EPUB), mapping each resolved image URL to
+/// the caching headers returned for it.
+pub struct ImageIndex {
+    path: PathBuf,
+    entries: Mutex<HashMap<String, ImageCacheEntry>>,
+}
+
+impl ImageIndex {
+    /// Load the index from `path`, or start an empty one if it doesn't
+    /// exist yet.
+    pub fn load(path: PathBuf) -> Arc<Self> {
+        let entries = std::fs::read(&path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+
+        Arc::new(Self {
+            path,
+            entries: Mutex::new(entries),
+        })
+    }
+
+    pub async fn get(&self, image_url: &str) -> Option<ImageCacheEntry> {
+        self.entries.lock().await.get(image_url).cloned()
+    }
+
+    pub async fn set(&self, image_url: String, entry: ImageCacheEntry) -> Result<()> {
+        let mut entries = self.entries.lock().await;
+        entries.insert(image_url, entry);
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, serde_json::to_vec_pretty(&*entries)?)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_index_roundtrip() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("gallery.index.json");
+        let index = ImageIndex::load(path.clone());
+        assert!(index.get("https://example.com/1").await.is_none());
+
+        index
+            .set(
+                "https://example.com/1".to_string(),
+                ImageCacheEntry {
+                    etag: Some("abc".to_string()),
+                    last_modified: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        let reloaded = ImageIndex::load(path);
+        let entry = reloaded.get("https://example.com/1").await.unwrap();
+        assert_eq!(entry.etag.as_deref(), Some("abc"));
+    }
+}
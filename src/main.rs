@@ -7,15 +7,22 @@ use anyhow::{Context, Result};
 use clap::Parser;
 use indicatif::{MultiProgress, ProgressBar};
 use reqwest::{Client, Proxy};
+use tokio::task::JoinSet;
 
 use crate::config::Config;
 
+mod adapters;
 mod config;
 mod gallery;
+mod http;
+mod index;
+mod metadata;
+mod package;
 mod utils;
 
 static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
 static SEM: OnceLock<Arc<tokio::sync::Semaphore>> = OnceLock::new();
+static GALLERY_SEM: OnceLock<Arc<tokio::sync::Semaphore>> = OnceLock::new();
 static PB: LazyLock<MultiProgress> = LazyLock::new(MultiProgress::new);
 
 #[derive(Parser, Debug)]
@@ -38,7 +45,7 @@ async fn main() -> Result<()> {
         std::process::exit(1);
     })?;
 
-    let gallerys = config.get_links().map_err(|e| {
+    let gallerys = config.get_links().await.map_err(|e| {
         eprintln!("Error reading input file: {}", e);
         std::process::exit(1);
     })?;
@@ -52,9 +59,19 @@ async fn main() -> Result<()> {
             .progress_chars("=>-"),
     );
 
+    let mut tasks = JoinSet::new();
     for g in gallerys {
         let config = Arc::clone(&config);
-        g.download(config).await;
+        tasks.spawn(async move {
+            let _limit = GALLERY_SEM.get().unwrap().acquire().await;
+            g.download(config).await;
+        });
+    }
+
+    while let Some(result) = tasks.join_next().await {
+        if let Err(e) = result {
+            error!("Gallery task panicked: {}", e);
+        }
         pb.inc(1);
     }
 
@@ -82,5 +99,11 @@ fn init(config: &Config) -> Result<()> {
     SEM.set(Arc::new(semaphore))
         .expect("Failed to set the semaphore for concurrency control");
 
+    // Initialize GALLERY_SEM
+    let gallery_semaphore = tokio::sync::Semaphore::new(config.gallery_concurrency);
+    GALLERY_SEM
+        .set(Arc::new(gallery_semaphore))
+        .expect("Failed to set the semaphore for gallery-level concurrency control");
+
     Ok(())
 }
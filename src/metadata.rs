@@ -0,0 +1,51 @@
+use std::{fs, path::Path};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::adapters::GalleryMetadataFields;
+
+/// One page's resolved output file, for mapping page numbers to files.
+#[derive(Debug, Serialize)]
+pub struct ImageManifestEntry {
+    pub page: usize,
+    pub file_name: String,
+}
+
+/// A gallery's metadata manifest, written as `metadata.json` alongside its
+/// downloaded images.
+#[derive(Debug, Serialize)]
+pub struct GalleryMetadata {
+    pub title: String,
+    pub url: String,
+    pub uploader: Option<String>,
+    pub category: Option<String>,
+    pub tags: Vec<String>,
+    pub page_count: Option<u32>,
+    pub images: Vec<ImageManifestEntry>,
+}
+
+impl GalleryMetadata {
+    pub fn new(
+        title: String,
+        url: String,
+        fields: GalleryMetadataFields,
+        images: Vec<ImageManifestEntry>,
+    ) -> Self {
+        Self {
+            title,
+            url,
+            uploader: fields.uploader,
+            category: fields.category,
+            tags: fields.tags,
+            page_count: fields.page_count,
+            images,
+        }
+    }
+
+    pub fn write_to(&self, output_dir: &Path) -> Result<()> {
+        let path = output_dir.join("metadata.json");
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+}
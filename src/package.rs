@@ -0,0 +1,280 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use zip::{CompressionMethod, ZipWriter, write::FileOptions};
+
+use crate::config::{Config, PackageFormat};
+
+/// Package a finished gallery's output directory into the format selected
+/// by [`Config::package`], then delete the loose files. A no-op for
+/// [`PackageFormat::Folder`].
+pub fn package_gallery(config: &Config, title: &str, url: &str, output_dir: &Path) -> Result<()> {
+    match config.package {
+        PackageFormat::Folder => return Ok(()),
+        PackageFormat::Cbz => package_cbz(title, output_dir)?,
+        PackageFormat::Epub => package_epub(title, url, output_dir)?,
+    }
+
+    fs::remove_dir_all(output_dir)?;
+
+    Ok(())
+}
+
+/// List the downloaded image files in page order (the `download` function
+/// names them `1.ext`, `2.ext`, ... in reading order). Sidecar files such
+/// as `metadata.json` are not numbered and are excluded.
+pub(crate) fn numbered_images(output_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files: Vec<(u32, PathBuf)> = fs::read_dir(output_dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter_map(|path| {
+            let page = path.file_stem()?.to_str()?.parse::<u32>().ok()?;
+            Some((page, path))
+        })
+        .collect();
+
+    files.sort_by_key(|(page, _)| *page);
+
+    Ok(files.into_iter().map(|(_, path)| path).collect())
+}
+
+fn package_cbz(title: &str, output_dir: &Path) -> Result<()> {
+    let archive_path = output_dir.with_file_name(format!("{title}.cbz"));
+    let file = fs::File::create(&archive_path)?;
+    let mut zip = ZipWriter::new(file);
+    let options = FileOptions::<()>::default().compression_method(CompressionMethod::Stored);
+
+    for image in numbered_images(output_dir)? {
+        let name = image
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("page");
+        zip.start_file(name, options)?;
+        zip.write_all(&fs::read(&image)?)?;
+    }
+
+    let metadata_path = output_dir.join("metadata.json");
+    if metadata_path.exists() {
+        zip.start_file("metadata.json", options)?;
+        zip.write_all(&fs::read(&metadata_path)?)?;
+    }
+
+    zip.finish()?;
+
+    Ok(())
+}
+
+fn mime_for(file_name: &str) -> &'static str {
+    match file_name.rsplit('.').next().unwrap_or("") {
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        _ => "image/jpeg",
+    }
+}
+
+fn package_epub(title: &str, url: &str, output_dir: &Path) -> Result<()> {
+    let archive_path = output_dir.with_file_name(format!("{title}.epub"));
+    let file = fs::File::create(&archive_path)?;
+    let mut zip = ZipWriter::new(file);
+
+    // The mimetype entry must be the first entry and must be stored
+    // uncompressed, per the EPUB OCF spec.
+    let stored = FileOptions::<()>::default().compression_method(CompressionMethod::Stored);
+    zip.start_file("mimetype", stored)?;
+    zip.write_all(b"application/epub+zip")?;
+
+    let deflated = FileOptions::<()>::default().compression_method(CompressionMethod::Deflated);
+
+    zip.start_file("META-INF/container.xml", deflated)?;
+    zip.write_all(CONTAINER_XML.as_bytes())?;
+
+    let images = numbered_images(output_dir)?;
+
+    let manifest_items = images
+        .iter()
+        .enumerate()
+        .map(|(i, image)| {
+            let name = image
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or("page");
+            format!(
+                "    <item id=\"img{i}\" href=\"images/{name}\" media-type=\"{}\"/>\n    <item id=\"page{i}\" href=\"page{i}.xhtml\" media-type=\"application/xhtml+xml\"/>",
+                mime_for(name)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let spine_items = (0..images.len())
+        .map(|i| format!("    <itemref idref=\"page{i}\"/>"))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let content_opf = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="2.0" unique-identifier="BookId">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:title>{title}</dc:title>
+    <dc:identifier id="BookId">{url}</dc:identifier>
+    <dc:source>{url}</dc:source>
+    <dc:language>en</dc:language>
+  </metadata>
+  <manifest>
+    <item id="ncx" href="toc.ncx" media-type="application/x-dtbncx+xml"/>
+{manifest_items}
+  </manifest>
+  <spine toc="ncx">
+{spine_items}
+  </spine>
+</package>
+"#
+    );
+    zip.start_file("content.opf", deflated)?;
+    zip.write_all(content_opf.as_bytes())?;
+
+    let nav_points = (0..images.len())
+        .map(|i| {
+            format!(
+                "    <navPoint id=\"navpoint-{i}\" playOrder=\"{}\">\n      <navLabel><text>Page {}</text></navLabel>\n      <content src=\"page{i}.xhtml\"/>\n    </navPoint>",
+                i + 1,
+                i + 1
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let toc_ncx = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/" version="2005-1">
+  <head>
+    <meta name="dtb:uid" content="{url}"/>
+  </head>
+  <docTitle><text>{title}</text></docTitle>
+  <navMap>
+{nav_points}
+  </navMap>
+</ncx>
+"#
+    );
+    zip.start_file("toc.ncx", deflated)?;
+    zip.write_all(toc_ncx.as_bytes())?;
+
+    let metadata_path = output_dir.join("metadata.json");
+    if metadata_path.exists() {
+        zip.start_file("metadata.json", deflated)?;
+        zip.write_all(&fs::read(&metadata_path)?)?;
+    }
+
+    for (i, image) in images.iter().enumerate() {
+        let name = image
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("page");
+
+        zip.start_file(format!("images/{name}"), stored)?;
+        zip.write_all(&fs::read(image)?)?;
+
+        let page_xhtml = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml">
+  <head><title>Page {page}</title></head>
+  <body>
+    <img src="images/{name}" alt="Page {page}"/>
+  </body>
+</html>
+"#,
+            page = i + 1,
+        );
+        zip.start_file(format!("page{i}.xhtml"), deflated)?;
+        zip.write_all(page_xhtml.as_bytes())?;
+    }
+
+    zip.finish()?;
+
+    Ok(())
+}
+
+const CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container xmlns="urn:oasis:names:tc:opendocument:xmlns:container" version="1.0">
+  <rootfiles>
+    <rootfile full-path="content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_package_cbz() {
+        let dir = tempdir().unwrap();
+        let gallery_dir = dir.path().join("My Gallery");
+        fs::create_dir_all(&gallery_dir).unwrap();
+        fs::write(gallery_dir.join("1.jpg"), b"fake-image-1").unwrap();
+        fs::write(gallery_dir.join("2.jpg"), b"fake-image-2").unwrap();
+
+        package_cbz("My Gallery", &gallery_dir).unwrap();
+
+        let archive_path = dir.path().join("My Gallery.cbz");
+        assert!(archive_path.exists());
+
+        let file = fs::File::open(&archive_path).unwrap();
+        let mut archive = zip::ZipArchive::new(file).unwrap();
+        assert_eq!(archive.len(), 2);
+        assert!(archive.by_name("1.jpg").is_ok());
+    }
+
+    #[test]
+    fn test_package_epub() {
+        let dir = tempdir().unwrap();
+        let gallery_dir = dir.path().join("My Gallery");
+        fs::create_dir_all(&gallery_dir).unwrap();
+        fs::write(gallery_dir.join("1.jpg"), b"fake-image-1").unwrap();
+        fs::write(gallery_dir.join("2.jpg"), b"fake-image-2").unwrap();
+        fs::write(gallery_dir.join("metadata.json"), b"{\"title\":\"My Gallery\"}").unwrap();
+
+        package_epub("My Gallery", "https://e-hentai.org/g/1/1/", &gallery_dir).unwrap();
+
+        let archive_path = dir.path().join("My Gallery.epub");
+        assert!(archive_path.exists());
+
+        let file = fs::File::open(&archive_path).unwrap();
+        let mut archive = zip::ZipArchive::new(file).unwrap();
+
+        let mut container_xml = String::new();
+        archive
+            .by_name("META-INF/container.xml")
+            .unwrap()
+            .read_to_string(&mut container_xml)
+            .unwrap();
+        assert!(container_xml.contains(r#"media-type="application/oebps-package+xml""#));
+
+        let mut content_opf = String::new();
+        archive
+            .by_name("content.opf")
+            .unwrap()
+            .read_to_string(&mut content_opf)
+            .unwrap();
+        assert!(content_opf.contains(r#"media-type="application/x-dtbncx+xml""#));
+        assert!(content_opf.contains(r#"media-type="image/jpeg""#));
+        assert!(content_opf.contains(r#"media-type="application/xhtml+xml""#));
+
+        let mut metadata_json = String::new();
+        archive
+            .by_name("metadata.json")
+            .unwrap()
+            .read_to_string(&mut metadata_json)
+            .unwrap();
+        assert_eq!(metadata_json, r#"{"title":"My Gallery"}"#);
+    }
+}